@@ -5,7 +5,76 @@
 //! more easily used internally (from the main application flow).
 use crate::filters::FilterKind;
 use clap::{Arg, ArgEnum, Command};
+use clap_complete::Shell;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+/// Config file layered beneath the environment and above built-in
+/// defaults, so users can pin their preferred settings without retyping
+/// flags on every invocation. Read from an XDG config path and merged in
+/// `Options::from` with precedence argv > env > file > default.
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    filter: Option<String>,
+    invert: Option<bool>,
+    statistics: Option<bool>,
+}
+
+impl ConfigFile {
+    /// Loads the config file, if one exists and parses successfully.
+    ///
+    /// Any failure to find or parse the file is treated the same as an
+    /// absent file: config is purely an optional convenience layer, so we
+    /// fall through to environment variables and built-in defaults rather
+    /// than erroring out.
+    fn load() -> ConfigFile {
+        fs::read_to_string(ConfigFile::path())
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    /// Resolves the XDG config path for runiq's config file.
+    fn path() -> PathBuf {
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .unwrap_or_default();
+
+        base.join("runiq").join("config.toml")
+    }
+}
+
+/// Parses a `FilterKind` from a string sourced from the environment or the
+/// config file, ignoring case the same way the CLI's `--filter` does.
+fn str_to_filter(value: &str) -> Option<FilterKind> {
+    FilterKind::from_str(value, true).ok()
+}
+
+/// Reads a `FilterKind` from an environment variable, if set and valid.
+fn env_filter(key: &str) -> Option<FilterKind> {
+    env::var(key).ok().and_then(|value| str_to_filter(&value))
+}
+
+/// Resolves a boolean flag with precedence argv > env > file > default.
+fn resolve_bool(present: bool, env_key: &str, file_value: Option<bool>) -> bool {
+    if present {
+        return true;
+    }
+
+    if let Ok(value) = env::var(env_key) {
+        if let Ok(parsed) = value.parse::<bool>() {
+            return parsed;
+        }
+    }
+
+    file_value.unwrap_or(false)
+}
 
 /// Options struct to store configuration state.
 ///
@@ -19,14 +88,115 @@ pub struct Options {
     pub inputs: Vec<String>,
     pub inverted: bool,
     pub statistics: bool,
+    /// Prefix each emitted entry with how many times it occurred.
+    ///
+    /// Counting defers emission until end-of-input, since the final count
+    /// for an entry isn't known until the whole input has been consumed;
+    /// this forfeits the constant-memory streaming guarantee of the plain
+    /// digest filter in favour of a `HashMap` of occurrence counts.
+    pub count: bool,
+    /// Emit only entries seen at least this many times (default 2 when the
+    /// flag is given without a value). Combined with `inverted`, emits only
+    /// entries seen fewer than this many times instead.
+    pub repeated: Option<u64>,
+    /// Number of leading fields to drop before computing a uniqueness key.
+    pub skip_fields: usize,
+    /// Number of leading characters to drop (after skipping fields) before
+    /// computing a uniqueness key.
+    pub skip_chars: usize,
+    /// Delimiter used to split fields for `skip_fields`. `None` means runs
+    /// of whitespace, matching the default behaviour of `uniq -f`.
+    pub delimiter: Option<char>,
+    /// Destination for first-seen unique entries. `None` means stdout.
+    pub output: Option<PathBuf>,
+    /// Destination for subsequent duplicate entries, written alongside
+    /// `output` in a single pass so a dataset can be partitioned into
+    /// "kept" and "dropped" files without running the tool twice.
+    pub duplicates: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            filter: FilterKind::Digest,
+            inputs: Vec::new(),
+            inverted: false,
+            statistics: false,
+            count: false,
+            repeated: None,
+            skip_fields: 0,
+            skip_chars: 0,
+            delimiter: None,
+            output: None,
+            duplicates: None,
+        }
+    }
+}
+
+/// Builder for `Options`, for callers embedding runiq's dedup engine
+/// in-process (e.g. a long-running service deduping streams) instead of
+/// driving it through command-line argument parsing.
+#[derive(Clone, Debug, Default)]
+pub struct OptionsBuilder {
+    options: Options,
+}
+
+impl OptionsBuilder {
+    /// Sets the filter used to determine uniqueness.
+    pub fn filter(mut self, filter: FilterKind) -> Self {
+        self.options.filter = filter;
+        self
+    }
+
+    /// Adds an input source to filter.
+    pub fn input(mut self, input: impl Into<String>) -> Self {
+        self.options.inputs.push(input.into());
+        self
+    }
+
+    /// Sets whether duplicates are printed instead of uniques.
+    pub fn inverted(mut self, inverted: bool) -> Self {
+        self.options.inverted = inverted;
+        self
+    }
+
+    /// Sets whether statistics are printed instead of entries.
+    pub fn statistics(mut self, statistics: bool) -> Self {
+        self.options.statistics = statistics;
+        self
+    }
+
+    /// Consumes the builder, producing the finished `Options`.
+    pub fn build(self) -> Options {
+        self.options
+    }
+}
+
+/// Outcome of parsing the command line.
+///
+/// Most invocations want to proceed with the regular dedup flow, but a
+/// meta-command such as `completions` produces its own output and exits
+/// before any inputs are touched, so there's no `Options` to hand back.
+#[derive(Debug)]
+pub enum ParsedOptions {
+    /// Proceed with the regular dedup flow using these options.
+    Run(Options),
+    /// A meta-command has already been handled; nothing left to do.
+    Handled,
 }
 
 impl Options {
-    /// Creates an `Options` struct from an iterable set of arguments.
+    /// Creates a builder for constructing `Options` directly, without
+    /// parsing command-line arguments through `clap`.
+    pub fn builder() -> OptionsBuilder {
+        OptionsBuilder::default()
+    }
+
+    /// Creates a `ParsedOptions` from an iterable set of arguments.
     ///
     /// Arguments can be any kind of iterator, as long as they can be
     /// successfully cloned and parsed into an instance of `OsString`.
-    pub fn from<I, T>(args: I) -> Options
+    pub fn from<I, T>(args: I) -> ParsedOptions
     where
         I: IntoIterator<Item = T>,
         T: Into<OsString> + Clone,
@@ -37,19 +207,75 @@ impl Options {
         // parse out the arguments into matching opts
         let options = parser.get_matches_from(args);
 
+        // a `completions` invocation is a meta-command: emit the script
+        // for the requested shell and stop before the dedup flow runs
+        if let Some(("completions", matches)) = options.subcommand() {
+            let shell = matches
+                .value_of_t::<Shell>("shell")
+                .unwrap_or_else(|e| e.exit());
+
+            let mut parser = Options::create_parser();
+            let name = parser.get_name().to_owned();
+
+            clap_complete::generate(shell, &mut parser, name, &mut io::stdout());
+
+            return ParsedOptions::Handled;
+        }
+
+        // config file and environment provide defaults beneath argv; load
+        // them once up front so argv can always override without caring
+        // where the fallback came from
+        let config = ConfigFile::load();
+
         // attempt to parse the provided filter
-        let filter = options.value_of_t::<FilterKind>("filter");
+        let filter = options.value_of_t::<FilterKind>("filter").ok();
 
         // create opts
-        Options {
-            // grab and store statistics flags
-            statistics: options.is_present("statistics"),
+        ParsedOptions::Run(Options {
+            // grab and store statistics flags: argv > env > file > default
+            statistics: resolve_bool(
+                options.is_present("statistics"),
+                "RUNIQ_STATISTICS",
+                config.statistics,
+            ),
+
+            // grab and store inversion flags: argv > env > file > default
+            inverted: resolve_bool(options.is_present("invert"), "RUNIQ_INVERT", config.invert),
+
+            // store the filter to use for unique detection: argv > env > file > default
+            filter: filter
+                .or_else(|| env_filter("RUNIQ_FILTER"))
+                .or_else(|| config.filter.as_deref().and_then(str_to_filter))
+                .unwrap_or(FilterKind::Digest),
+
+            // grab and store the occurrence-counting flag
+            count: options.is_present("count"),
+
+            // grab and store the repeat threshold, if requested
+            repeated: if options.is_present("repeated") {
+                Some(
+                    options
+                        .value_of_t::<u64>("repeated")
+                        .unwrap_or_else(|e| e.exit()),
+                )
+            } else {
+                None
+            },
+
+            // grab and store the field-skip count
+            skip_fields: options.value_of_t::<usize>("skip-fields").unwrap_or(0),
+
+            // grab and store the char-skip count
+            skip_chars: options.value_of_t::<usize>("skip-chars").unwrap_or(0),
+
+            // grab and store the field delimiter, if overridden
+            delimiter: options.value_of_t::<char>("delimiter").ok(),
 
-            // grab and store inversion flags
-            inverted: options.is_present("invert"),
+            // grab and store the unique-output destination, if redirected
+            output: options.value_of_t::<PathBuf>("output").ok(),
 
-            // store the filter to use for unique detection
-            filter: filter.unwrap_or(FilterKind::Digest),
+            // grab and store the duplicate-output destination, if given
+            duplicates: options.value_of_t::<PathBuf>("duplicates").ok(),
 
             // own all inputs
             inputs: options
@@ -57,7 +283,128 @@ impl Options {
                 .unwrap()
                 .map(|s| s.to_owned())
                 .collect(),
+        })
+    }
+
+    /// Derives the uniqueness key for a line, per `skip_fields`/`skip_chars`.
+    ///
+    /// The full line is always what gets printed and fed to statistics;
+    /// only this derived slice is handed to the active `FilterKind` for
+    /// membership/hash detection. A line with fewer than `skip_fields`
+    /// fields, or with `skip_chars` beyond what remains, yields an empty
+    /// key, collapsing all such lines together.
+    pub fn extract_key<'a>(&self, line: &'a str) -> &'a str {
+        let residual = self.skip_leading_fields(line);
+
+        match residual.char_indices().nth(self.skip_chars) {
+            Some((idx, _)) => &residual[idx..],
+            None => "",
+        }
+    }
+
+    /// Runs the count/repeat-threshold dedup flow over `lines`, returning
+    /// the entries to emit in first-seen order.
+    ///
+    /// Used whenever `count` or `repeated` is set, in place of the plain
+    /// streaming filter: the final count for an entry isn't known until
+    /// the whole input has been consumed, so emission is deferred and
+    /// entries are walked back in first-seen order once input ends.
+    pub fn execute_counted<I>(&self, lines: I) -> Vec<String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut index: HashMap<String, usize> = HashMap::new();
+        let mut entries: Vec<(String, u64)> = Vec::new();
+
+        for line in lines {
+            let key = self.extract_key(&line).to_owned();
+
+            match index.get(&key) {
+                Some(&position) => entries[position].1 += 1,
+                None => {
+                    index.insert(key, entries.len());
+                    entries.push((line, 1));
+                }
+            }
+        }
+
+        let threshold = self.repeated.unwrap_or(1);
+
+        entries
+            .into_iter()
+            .filter(|(_, occurrences)| match self.repeated {
+                Some(_) if self.inverted => *occurrences < threshold,
+                Some(_) => *occurrences >= threshold,
+                None => true,
+            })
+            .map(|(line, occurrences)| {
+                if self.count {
+                    format!("{}\t{}", occurrences, line)
+                } else {
+                    line
+                }
+            })
+            .collect()
+    }
+
+    /// Drops the first `skip_fields` fields from `line`, where fields are
+    /// separated by runs of `delimiter` (or whitespace, if unset).
+    fn skip_leading_fields<'a>(&self, line: &'a str) -> &'a str {
+        let is_delim = |c: char| self.delimiter.map_or(c.is_whitespace(), |d| c == d);
+        let mut rest = line;
+
+        for _ in 0..self.skip_fields {
+            rest = rest.trim_start_matches(is_delim);
+            match rest.find(is_delim) {
+                Some(idx) => rest = &rest[idx..],
+                None => return "",
+            }
+        }
+
+        rest.trim_start_matches(is_delim)
+    }
+
+    /// Opens the configured output destinations.
+    ///
+    /// `output` falls back to stdout when unset; `duplicates` is `None`
+    /// when unset, in which case duplicates are simply dropped, matching
+    /// the default (non-`--invert`) behaviour of only emitting uniques.
+    pub fn open_writers(&self) -> io::Result<(Box<dyn Write>, Option<Box<dyn Write>>)> {
+        let output: Box<dyn Write> = match &self.output {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(BufWriter::new(io::stdout())),
+        };
+
+        let duplicates = match &self.duplicates {
+            Some(path) => Some(Box::new(BufWriter::new(File::create(path)?)) as Box<dyn Write>),
+            None => None,
+        };
+
+        Ok((output, duplicates))
+    }
+
+    /// Partitions `lines` into first-seen uniques and subsequent
+    /// duplicates in a single pass, writing each to its own destination
+    /// per `output`/`duplicates` so a dataset can be split into "kept"
+    /// and "dropped" files without running the tool twice.
+    pub fn execute_partitioned<I>(&self, lines: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let (mut output, mut duplicates) = self.open_writers()?;
+        let mut seen = HashSet::new();
+
+        for line in lines {
+            let key = self.extract_key(&line).to_owned();
+
+            if seen.insert(key) {
+                writeln!(output, "{}", line)?;
+            } else if let Some(writer) = duplicates.as_mut() {
+                writeln!(writer, "{}", line)?;
+            }
         }
+
+        Ok(())
     }
 
     /// Creates a parser used to generate `Options`.
@@ -103,10 +450,150 @@ impl Options {
                     .help("Prints statistics instead of entries")
                     .short('s')
                     .long("statistics"),
+                // count: -c --count
+                Arg::new("count")
+                    .help("Prefixes each entry with its occurrence count")
+                    .short('c')
+                    .long("count"),
+                // repeated: -d --repeated[=N]
+                Arg::new("repeated")
+                    .help("Only prints entries seen at least N times (default 2)")
+                    .short('d')
+                    .long("repeated")
+                    .takes_value(true)
+                    .min_values(0)
+                    .default_missing_value("2")
+                    .require_equals(true),
+                // skip-fields: -F, --skip-fields=N
+                // (uniq(1) uses -f here, but that's already --filter; -F is
+                // the closest available short flag)
+                Arg::new("skip-fields")
+                    .help("Avoids comparing the first N fields of each line")
+                    .short('F')
+                    .long("skip-fields")
+                    .takes_value(true),
+                // skip-chars: --skip-chars=M, no short flag
+                // (uniq(1) uses -s here, but that's already --statistics)
+                Arg::new("skip-chars")
+                    .help("Avoids comparing the first M characters of each line")
+                    .long("skip-chars")
+                    .takes_value(true),
+                // delimiter: --delimiter=CHAR
+                Arg::new("delimiter")
+                    .help("Field delimiter used by --skip-fields (default: whitespace)")
+                    .long("delimiter")
+                    .takes_value(true),
+                // output: -o, --output=FILE
+                Arg::new("output")
+                    .help("Writes unique entries to FILE instead of stdout")
+                    .short('o')
+                    .long("output")
+                    .takes_value(true),
+                // duplicates: --duplicates=FILE
+                Arg::new("duplicates")
+                    .help("Writes duplicate entries to FILE, alongside --output")
+                    .long("duplicates")
+                    .takes_value(true),
             ])
+            // completions: emits a shell completion script and exits
+            .subcommand(
+                Command::new("completions")
+                    .about("Generates a shell completion script")
+                    .arg(
+                        Arg::new("shell")
+                            .help("Shell to generate a completion script for")
+                            .required(true)
+                            .possible_values(
+                                Shell::value_variants()
+                                    .iter()
+                                    .filter_map(ArgEnum::to_possible_value),
+                            ),
+                    ),
+            )
             // settings required for parsing
             .arg_required_else_help(true)
             .hide_possible_values(true)
+            .subcommand_negates_reqs(true)
             .trailing_var_arg(true)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options_with(skip_fields: usize, skip_chars: usize, delimiter: Option<char>) -> Options {
+        Options {
+            skip_fields,
+            skip_chars,
+            delimiter,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn extract_key_defaults_to_the_whole_line() {
+        let options = options_with(0, 0, None);
+        assert_eq!(options.extract_key("2026-01-01 GET /"), "2026-01-01 GET /");
+    }
+
+    #[test]
+    fn extract_key_skips_leading_whitespace_fields() {
+        let options = options_with(2, 0, None);
+        assert_eq!(options.extract_key("2026-01-01 12:00:00 GET /"), "GET /");
+    }
+
+    #[test]
+    fn extract_key_skips_chars_after_fields() {
+        let options = options_with(1, 3, None);
+        assert_eq!(
+            options.extract_key("host subdomain.example.com"),
+            "domain.example.com"
+        );
+    }
+
+    #[test]
+    fn extract_key_honours_a_custom_delimiter() {
+        let options = options_with(2, 0, Some(','));
+        assert_eq!(options.extract_key("a,b,c,d"), "c,d");
+    }
+
+    #[test]
+    fn extract_key_collapses_lines_with_too_few_fields() {
+        let options = options_with(5, 0, None);
+        assert_eq!(options.extract_key("only two fields"), "");
+        assert_eq!(
+            options.extract_key("only two fields"),
+            options.extract_key("a b")
+        );
+    }
+
+    #[test]
+    fn extract_key_collapses_when_skip_chars_exceeds_the_residual() {
+        let options = options_with(0, 100, None);
+        assert_eq!(options.extract_key("short"), "");
+    }
+
+    #[test]
+    fn resolve_bool_respects_argv_env_file_precedence() {
+        // all precedence levels share one process-global env var, so this
+        // stays a single test rather than several that could race.
+        let key = "RUNIQ_TEST_RESOLVE_BOOL";
+        env::remove_var(key);
+
+        // built-in default, nothing else set
+        assert!(!resolve_bool(false, key, None));
+
+        // file value is used once env and argv are both absent
+        assert!(resolve_bool(false, key, Some(true)));
+
+        // env overrides the file value
+        env::set_var(key, "false");
+        assert!(!resolve_bool(false, key, Some(true)));
+
+        // argv overrides both env and file
+        assert!(resolve_bool(true, key, Some(false)));
+
+        env::remove_var(key);
+    }
+}